@@ -1,19 +1,24 @@
 pub mod post;
 pub mod post_information;
+pub mod store;
 
-use bsky_parser::{BskyCreds, Did, ThreadData};
+use bsky_parser::{BskyCreds, Did, ThreadData, ThreadViewPost};
 use dotenvy::Error as DotEnvError;
-use image::EncodableLayout;
-use regex::Error as RegexError;
+use futures_util::StreamExt;
+use regex::{Error as RegexError, Regex};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, ToStrError}, redirect::Policy, ClientBuilder, Error as ReqwestError
+    header::{HeaderMap, HeaderValue, ToStrError}, redirect::Policy, Client, ClientBuilder, Error as ReqwestError, Response
 };
+use serde::Serialize;
 use serde_json::{Error as SerdeError, json};
-use std::{env::VarError, fmt::Display, fs::{self, OpenOptions}, io::{Error as IoError, Read, Write}, net::TcpStream, num::ParseIntError, os::windows::fs::FileExt, process::exit};
-use tokio::{fs::File, io::{AsyncReadExt, AsyncWriteExt, BufWriter}};
+use std::{env::VarError, fmt::Display, io::Error as IoError, net::TcpStream, num::ParseIntError, path::PathBuf, process::exit};
 use native_tls::{Error as NativeTlsError, HandshakeError};
+use tokio::{fs::File as TokioFile, io::{AsyncWriteExt, BufWriter}};
 
 use crate::lib::post::Post;
+use crate::lib::store::{sharded_blob_key, store_from_env, MediaStore};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 
 #[derive(Debug)]
 pub enum MediaType {
@@ -43,11 +48,76 @@ impl<'a> Into<&'a str> for MediaType {
             Self::Mov => "mov",
             Self::Webm => "webm",
             Self::Mpeg => "mpeg",
-            Self::Invalid => "Invalid media type"
+            Self::Invalid => "bin"
         }
     }
 }
 
+/// Sniffs a video container from its leading bytes, since the MIME string
+/// Bluesky reports is frequently missing or wrong. Returns `None` when the
+/// header doesn't match any recognized signature.
+fn sniff_video_extension(header: &[u8]) -> Option<&'static str> {
+    if header.len() >= 8 && &header[4..8] == b"ftyp" {
+        let major_brand = &header[8..12.min(header.len())];
+        return Some(match major_brand {
+            b"qt  " => "mov",
+            _ => "mp4",
+        });
+    }
+
+    if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return Some("webm");
+    }
+
+    if header.starts_with(&[0x00, 0x00, 0x01, 0xBA]) || header.starts_with(&[0x00, 0x00, 0x01, 0xB3]) {
+        return Some("mpeg");
+    }
+
+    None
+}
+
+/// Maps a sniffed video extension to its proper MIME type. Most containers'
+/// MIME type is just `video/<ext>`, but `.mov` is the one standard exception
+/// (`video/quicktime`), so it can't be derived by interpolating the
+/// extension the way [`sniff_video_extension`]'s other results can.
+fn video_mime_type(ext: &str) -> String {
+    match ext {
+        "mov" => "video/quicktime".to_string(),
+        _ => format!("video/{ext}"),
+    }
+}
+
+/// Maps a sniffed image extension to its proper MIME type. Most formats'
+/// MIME type is just `image/<ext>`, but a couple of `image::ImageFormat`'s
+/// extensions aren't registered IANA subtypes as-is (`jpg` is really
+/// `jpeg`, and `.ico` is `image/vnd.microsoft.icon`), so they can't be
+/// derived by interpolating the extension the way [`sniff_image_extension`]'s
+/// other results can.
+fn image_mime_type(ext: &str) -> String {
+    match ext {
+        "jpg" => "image/jpeg".to_string(),
+        "ico" => "image/vnd.microsoft.icon".to_string(),
+        _ => format!("image/{ext}"),
+    }
+}
+
+/// Sniffs an image's real encoding from its magic bytes, since the server
+/// doesn't always say and blobs were previously always written as `.png`
+/// regardless of what they actually were.
+fn sniff_image_extension(header: &[u8]) -> Option<&'static str> {
+    image::guess_format(header).ok().map(|format| match format {
+        image::ImageFormat::Png => "png",
+        image::ImageFormat::Jpeg => "jpg",
+        image::ImageFormat::Gif => "gif",
+        image::ImageFormat::WebP => "webp",
+        image::ImageFormat::Bmp => "bmp",
+        image::ImageFormat::Tiff => "tiff",
+        image::ImageFormat::Ico => "ico",
+        image::ImageFormat::Avif => "avif",
+        _ => "png",
+    })
+}
+
 #[derive(Debug)]
 pub enum Errors {
     Reqwest(ReqwestError),
@@ -60,6 +130,7 @@ pub enum Errors {
     Handshake(HandshakeError<TcpStream>),
     ToStr(ToStrError),
     ParseInt(ParseIntError),
+    Store(String),
 }
 
 impl From<ReqwestError> for Errors {
@@ -135,18 +206,546 @@ impl Display for Errors {
             Self::Handshake(error) => write!(f, "Unable to successfully complete TCP handshake => {error}"),
             Self::ToStr(error) => write!(f, "Unable to convert to str => {error}"),
             Self::ParseInt(error) => write!(f, "Could not parse integer => {error}"),
+            Self::Store(error) => write!(f, "Media store error => {error}"),
         }
     }
 }
 
-pub async fn archive(post_info: Post) -> Result<(), Errors> {
-    let captures = &post_info.post_id_regex.captures(&post_info.info.url);
+/// One row of a thread's `index.json`: which post this is and which post
+/// (if any) it replies to, so the reply/ancestor structure survives being
+/// flattened into per-post directories.
+#[derive(Debug, Serialize)]
+struct ThreadIndexEntry {
+    uri: String,
+    dir: String,
+    parent_uri: Option<String>,
+}
+
+/// The AT-URI rkey (last path segment) a post is archived under.
+fn rkey_from_uri(uri: &str) -> &str {
+    uri.rsplit('/').next().unwrap_or(uri)
+}
+
+/// The author DID embedded in an AT-URI (`at://<did>/<collection>/<rkey>`).
+fn did_from_uri(uri: &str) -> &str {
+    uri.split('/').nth(2).unwrap_or(uri)
+}
+
+/// Reconstructs the bsky.app URL for a thread node from its own `post.uri`,
+/// so every archived post -- not just the one the user originally pasted a
+/// URL for -- gets a real, externally-linkable address to submit to the
+/// Wayback Machine. `bsky.app` resolves DIDs in the profile segment just as
+/// well as handles, so this needs no extra handle-resolution lookup.
+fn bsky_post_url(uri: &str) -> String {
+    format!("https://bsky.app/profile/{}/post/{}", did_from_uri(uri), rkey_from_uri(uri))
+}
+
+/// Where the archive-wide CID -> blob key map lives, so the same image or
+/// video reposted/quoted across many posts is only ever downloaded once.
+const CID_INDEX_KEY: &str = "blobs/cid-index.json";
+
+/// Walks a raw (untyped) `getPostThread` response and indexes every node's
+/// verbatim `post` object by URI, so each node's `raw.json` can keep the
+/// server's exact bytes instead of a re-serialization of only the fields
+/// `bsky_parser` models.
+fn collect_raw_thread_posts(node: &serde_json::Value, out: &mut HashMap<String, serde_json::Value>) {
+    let Some(post) = node.get("post") else {
+        return;
+    };
+    if let Some(uri) = post.get("uri").and_then(|uri| uri.as_str()) {
+        out.insert(uri.to_string(), post.clone());
+    }
+
+    if let Some(parent) = node.get("parent") {
+        collect_raw_thread_posts(parent, out);
+    }
+    if let Some(replies) = node.get("replies").and_then(|replies| replies.as_array()) {
+        for reply in replies {
+            collect_raw_thread_posts(reply, out);
+        }
+    }
+}
+
+/// How many leading bytes to keep around for magic-byte sniffing. Generous
+/// enough for an MP4 `ftyp` box (needs 12) or any of the image signatures
+/// `image::guess_format` looks for.
+const SNIFF_HEADER_LEN: usize = 32;
+
+/// How often to log download progress, in bytes. A multi-hundred-MB video
+/// streamed in typical chunk sizes would otherwise print on every single
+/// chunk, which defeats the point of switching to streaming in the first
+/// place.
+const PROGRESS_LOG_INTERVAL_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Streams `response`'s body straight to a temp file (rather than buffering
+/// the whole blob in memory), hashing it as it goes. Returns the temp file's
+/// path, the hex SHA-256 digest of its contents, and its leading bytes for
+/// magic-byte sniffing, so the caller can move it into content-addressed
+/// storage once the final key (which depends on both) is known.
+///
+/// `getBlob` redirects to the PDS/CDN are already followed by the client's
+/// redirect policy, so `response` here is the final, streamable body.
+async fn stream_blob_to_temp(
+    response: Response,
+    tmp_name: &str,
+) -> Result<(PathBuf, String, Vec<u8>), Errors> {
+    let total_bytes = response.content_length();
+    let tmp_path = std::env::temp_dir().join(tmp_name);
+    let mut writer = BufWriter::new(TokioFile::create(&tmp_path).await?);
+    let mut hasher = Sha256::new();
+    let mut header = Vec::with_capacity(SNIFF_HEADER_LEN);
+    let mut downloaded: u64 = 0;
+    let mut last_logged: u64 = 0;
+    let mut stream = response.bytes_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+
+        if header.len() < SNIFF_HEADER_LEN {
+            let take = (SNIFF_HEADER_LEN - header.len()).min(chunk.len());
+            header.extend_from_slice(&chunk[..take]);
+        }
+
+        if downloaded - last_logged >= PROGRESS_LOG_INTERVAL_BYTES {
+            last_logged = downloaded;
+            match total_bytes {
+                Some(total) => println!("Downloaded {downloaded}/{total} bytes"),
+                None => println!("Downloaded {downloaded} bytes"),
+            }
+        }
+    }
+    writer.flush().await?;
+
+    Ok((tmp_path, format!("{:x}", hasher.finalize()), header))
+}
+
+/// Archives a single node of a thread (its `raw.json` and media), then
+/// recurses into its parent and replies so the whole conversation ends up
+/// on disk, not just the post that was originally requested.
+async fn archive_thread_node(
+    node: &ThreadViewPost,
+    client: &Client,
+    did: &Did,
+    creds: &BskyCreds,
+    store: &dyn MediaStore,
+    parent_uri: Option<&str>,
+    index: &mut Vec<ThreadIndexEntry>,
+    raw_posts: &HashMap<String, serde_json::Value>,
+    cid_index: &mut HashMap<String, String>,
+) -> Result<(), Errors> {
+    let Some(post) = &node.post else {
+        return Ok(());
+    };
+    let post_dir = rkey_from_uri(&post.uri).to_string();
+
+    index.push(ThreadIndexEntry {
+        uri: post.uri.clone(),
+        dir: post_dir.clone(),
+        parent_uri: parent_uri.map(String::from),
+    });
+
+    println!("Saving post {post_dir} locally...");
+    // Write back the server's own bytes for this node rather than
+    // re-serializing the typed `Post`, so `raw.json` stays faithful to
+    // fields `bsky_parser` doesn't model.
+    let raw_bytes = match raw_posts.get(&post.uri) {
+        Some(raw) => serde_json::to_vec(raw)?,
+        None => serde_json::to_vec(post)?,
+    };
+    store
+        .raw_json(&format!("{post_dir}/raw.json"), &raw_bytes)
+        .await?;
+
+    println!("Archiving {post_dir} externally...");
+    let post_url = bsky_post_url(&post.uri);
+    let wayback_snapshot_url =
+        submit_to_wayback_machine(client, &format!("https://web.archive.org/save/{post_url}")).await?;
+    let metadata = ArchiveMetadata {
+        original_url: post_url,
+        did: did_from_uri(&post.uri).to_string(),
+        archived_at: chrono::Utc::now().to_rfc3339(),
+        wayback_snapshot_url,
+    };
+    store
+        .raw_json(&format!("{post_dir}/metadata.json"), &serde_json::to_vec(&metadata)?)
+        .await?;
+
+    if let Some(record) = &post.record {
+        println!("Raw post data archived...Saving associated media for {post_dir}...");
+
+        // Per-post record of which blobs this post embeds and where they
+        // ended up. The archive-wide `cid_index` (not this map) is what
+        // decides whether a blob needs downloading at all, since the same
+        // CID can show up again in an entirely different post.
+        let blob_map_key = format!("{post_dir}/blob_map.json");
+        let mut blob_map: HashMap<String, String> = match store.get(&blob_map_key).await? {
+            Some(bytes) => serde_json::from_slice(&bytes)?,
+            None => HashMap::new(),
+        };
+
+        if let Some(media) = &record.embed {
+            for image in &media.images {
+                let referer = &image.image.referer;
+                if let Some(blob_key) = cid_index.get(&referer.cid) {
+                    println!("Skipping already-archived blob {}", &referer.cid);
+                    blob_map.insert(referer.cid.clone(), blob_key.clone());
+                    continue;
+                }
+
+                let url = format!(
+                    "https://bsky.social/xrpc/com.atproto.sync.getBlob?did={}&cid={}",
+                    &did.did, &referer.cid
+                );
+                let response = client.get(&url).send().await?;
+                let (tmp_path, digest, header) =
+                    stream_blob_to_temp(response, &format!("{}.tmp", referer.cid)).await?;
+                let ext = sniff_image_extension(&header).unwrap_or("png");
+                let blob_key = sharded_blob_key(&digest, ext);
+                let mime_type = image_mime_type(ext);
+
+                if store.exists(&blob_key).await? {
+                    tokio::fs::remove_file(&tmp_path).await?;
+                } else {
+                    store.put_file(&blob_key, &tmp_path, &mime_type).await?;
+                }
+                blob_map.insert(referer.cid.clone(), blob_key.clone());
+                cid_index.insert(referer.cid.clone(), blob_key);
+                println!("Saved {}", &referer.cid)
+            }
+            if let Some(video) = &media.video {
+                println!("Saving video from post");
+
+                let referer = &video.referer;
+                if let Some(blob_key) = cid_index.get(&referer.cid) {
+                    println!("Skipping already-archived blob {}", &referer.cid);
+                    blob_map.insert(referer.cid.clone(), blob_key.clone());
+                } else {
+                    // A missing or unrecognized media type doesn't abort the
+                    // archive; it only matters if sniffing fails (see below).
+                    let server_mime_type = video.mime_type.as_str();
+                    println!("MEDIA TYPE => {server_mime_type}");
+                    let url_path = format!(
+                        "/xrpc/com.atproto.sync.getBlob?did={}&cid={}",
+                        &did.did, &referer.cid
+                    );
+
+                    // The client's redirect policy already follows the
+                    // PDS/CDN redirect, so this response's body can be
+                    // streamed straight through to disk.
+                    let reqwest_response = client
+                        .get(format!("https://bsky.social{url_path}"))
+                        .bearer_auth(&creds.access_jwt)
+                        .send()
+                        .await?;
+                    let (tmp_path, digest, header) =
+                        stream_blob_to_temp(reqwest_response, &format!("{}.tmp", referer.cid))
+                            .await?;
+
+                    // Only fall back to the server-reported MIME type when
+                    // the container's magic bytes are inconclusive.
+                    let (ext, mime_type) = match sniff_video_extension(&header) {
+                        Some(ext) => (ext, video_mime_type(ext)),
+                        None => {
+                            let media_type: &str = MediaType::from(server_mime_type).into();
+                            (media_type, server_mime_type.to_string())
+                        }
+                    };
+                    let blob_key = sharded_blob_key(&digest, ext);
+
+                    if store.exists(&blob_key).await? {
+                        tokio::fs::remove_file(&tmp_path).await?;
+                    } else {
+                        store.put_file(&blob_key, &tmp_path, &mime_type).await?;
+                    }
+                    blob_map.insert(referer.cid.clone(), blob_key.clone());
+                    cid_index.insert(referer.cid.clone(), blob_key);
+                }
+            }
+        }
+
+        store
+            .raw_json(&blob_map_key, &serde_json::to_vec(&blob_map)?)
+            .await?;
+    }
+
+    if let Some(parent) = &node.parent {
+        // `parent`'s own parent_uri is whatever it replies to, i.e. the
+        // next node up the chain -- never this post, which is `parent`'s
+        // *child* in the reply tree.
+        let grandparent_uri = parent
+            .parent
+            .as_ref()
+            .and_then(|grandparent| grandparent.post.as_ref())
+            .map(|post| post.uri.as_str());
+        Box::pin(archive_thread_node(
+            parent,
+            client,
+            did,
+            creds,
+            store,
+            grandparent_uri,
+            index,
+            raw_posts,
+            cid_index,
+        ))
+        .await?;
+    }
+
+    if let Some(replies) = &node.replies {
+        for reply in replies {
+            Box::pin(archive_thread_node(
+                reply,
+                client,
+                did,
+                creds,
+                store,
+                Some(&post.uri),
+                index,
+                raw_posts,
+                cid_index,
+            ))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A single `app.bsky.feed.getAuthorFeed` page. Only the fields the
+/// incremental poller needs are modeled here, since `bsky_parser` doesn't
+/// yet cover this endpoint.
+#[derive(Debug, serde::Deserialize)]
+struct AuthorFeedResponse {
+    feed: Vec<AuthorFeedItem>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AuthorFeedItem {
+    post: AuthorFeedPost,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AuthorFeedPost {
+    uri: String,
+    #[serde(rename = "indexedAt")]
+    indexed_at: String,
+}
+
+/// Tracks incremental progress through an account's feed across runs, so a
+/// re-run only archives posts newer than the last one already saved. Each
+/// run always starts paging from the newest post (no stored cursor) and
+/// walks forward until it reaches `last_seen_at`, since a stale page
+/// cursor would only let a run resume an interrupted backfill, not notice
+/// posts published since the last run.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct FeedState {
+    last_seen_at: Option<String>,
+}
+
+/// Records what was submitted to the Wayback Machine and what came back,
+/// alongside the local archive's own identifiers, so the citable external
+/// permalink travels with the post instead of only living in logs.
+#[derive(Debug, Serialize)]
+struct ArchiveMetadata {
+    original_url: String,
+    did: String,
+    archived_at: String,
+    wayback_snapshot_url: Option<String>,
+}
+
+/// Submits `save_url` (a `https://web.archive.org/save/<url>` Save Page Now
+/// request) and resolves the permanent snapshot URL from the response,
+/// either via the `Content-Location`/`Location` header or the final
+/// redirected URL once the client's redirect policy has followed it.
+async fn submit_to_wayback_machine(client: &Client, save_url: &str) -> Result<Option<String>, Errors> {
+    let response = client.get(save_url).send().await?;
+
+    let header_location = response
+        .headers()
+        .get("content-location")
+        .or_else(|| response.headers().get("location"))
+        .map(|value| value.to_str())
+        .transpose()?;
+    let final_url = response.url().to_string();
+
+    Ok(resolve_wayback_snapshot_url(header_location, &final_url))
+}
+
+/// Picks the snapshot URL out of a Save Page Now response: a
+/// `Content-Location`/`Location` header if present (relative ones are
+/// resolved against `web.archive.org`), otherwise the final redirected URL
+/// if and only if it actually landed on a `/web/` snapshot path.
+fn resolve_wayback_snapshot_url(header_location: Option<&str>, final_url: &str) -> Option<String> {
+    if let Some(location) = header_location {
+        return Some(if location.starts_with("http") {
+            location.to_string()
+        } else {
+            format!("https://web.archive.org{location}")
+        });
+    }
+
+    final_url.contains("/web/").then(|| final_url.to_string())
+}
+
+/// Archives a single post thread (root, ancestors, and replies) rooted at
+/// `rkey`, writing its `index.json` once the whole thread has been walked.
+async fn archive_post_thread(
+    client: &Client,
+    did: &Did,
+    creds: &BskyCreds,
+    store: &dyn MediaStore,
+    rkey: &str,
+    depth: u32,
+    parent_height: u32,
+) -> Result<(), Errors> {
+    let response = client
+        .get(format!(
+            "https://bsky.social/xrpc/app.bsky.feed.getPostThread?uri=at://{}/app.bsky.feed.post/{}&depth={}&parentHeight={}",
+            did.did, rkey, depth, parent_height
+        ))
+        .bearer_auth(&creds.access_jwt)
+        .send()
+        .await?
+        .bytes()
+        .await?
+        .to_vec();
+    let post_data = serde_json::from_slice::<ThreadData>(&response)?;
+    let raw_data = serde_json::from_slice::<serde_json::Value>(&response)?;
+    let mut raw_posts = HashMap::new();
+    if let Some(thread) = raw_data.get("thread") {
+        collect_raw_thread_posts(thread, &mut raw_posts);
+    }
 
-    // Exit code 100 means no post data could be extracted.
-    let post_info_pieces = match captures {
-        Some(captures) => captures,
-        None => exit(100),
+    // Loaded/saved around every thread so a blob already archived from some
+    // other post (a repost, a quote, the same image posted twice) is never
+    // downloaded twice.
+    let mut cid_index: HashMap<String, String> = match store.get(CID_INDEX_KEY).await? {
+        Some(bytes) => serde_json::from_slice(&bytes)?,
+        None => HashMap::new(),
     };
+
+    // The requested post is itself a reply as often as not, and its
+    // ancestor chain was just fetched via `parentHeight` -- so it has a
+    // real parent_uri too, not just the replies recursing into it.
+    let root_parent_uri = post_data
+        .thread
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.post.as_ref())
+        .map(|post| post.uri.as_str());
+
+    // Walk the root post, its full ancestor chain, and every nested reply,
+    // archiving each into its own directory instead of just the leaf post
+    // that was originally requested.
+    let mut index = Vec::new();
+    archive_thread_node(
+        &post_data.thread,
+        client,
+        did,
+        creds,
+        store,
+        root_parent_uri,
+        &mut index,
+        &raw_posts,
+        &mut cid_index,
+    )
+    .await?;
+
+    store
+        .raw_json(CID_INDEX_KEY, &serde_json::to_vec(&cid_index)?)
+        .await?;
+
+    if let Some(root) = index.first() {
+        store
+            .raw_json(&format!("{}/index.json", root.dir), &serde_json::to_vec(&index)?)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Pages through `handle`'s entire feed via `getAuthorFeed`, archiving every
+/// post into its own thread, and only visits posts newer than the last run
+/// recorded in the account's `state.json`.
+async fn archive_account_feed(
+    client: &Client,
+    did: &Did,
+    creds: &BskyCreds,
+    store: &dyn MediaStore,
+    handle: &str,
+    depth: u32,
+    parent_height: u32,
+) -> Result<(), Errors> {
+    let state_key = format!("accounts/{handle}/state.json");
+    let mut state: FeedState = match store.get(&state_key).await? {
+        Some(bytes) => serde_json::from_slice(&bytes)?,
+        None => FeedState::default(),
+    };
+
+    let mut cursor = None;
+    let mut newest_seen_at = None;
+
+    'paging: loop {
+        let mut url = format!(
+            "https://bsky.social/xrpc/app.bsky.feed.getAuthorFeed?actor={}&limit=100",
+            did.did
+        );
+        if let Some(cursor) = &cursor {
+            url.push_str(&format!("&cursor={cursor}"));
+        }
+
+        let response = client
+            .get(&url)
+            .bearer_auth(&creds.access_jwt)
+            .send()
+            .await?
+            .bytes()
+            .await?
+            .to_vec();
+        let feed = serde_json::from_slice::<AuthorFeedResponse>(&response)?;
+
+        if feed.feed.is_empty() {
+            break;
+        }
+
+        for item in &feed.feed {
+            if state
+                .last_seen_at
+                .as_deref()
+                .is_some_and(|last_seen_at| item.post.indexed_at.as_str() <= last_seen_at)
+            {
+                break 'paging;
+            }
+
+            if newest_seen_at.is_none() {
+                newest_seen_at = Some(item.post.indexed_at.clone());
+            }
+
+            let rkey = rkey_from_uri(&item.post.uri).to_string();
+            archive_post_thread(client, did, creds, store, &rkey, depth, parent_height).await?;
+        }
+
+        cursor = feed.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    if newest_seen_at.is_some() {
+        state.last_seen_at = newest_seen_at;
+    }
+    store
+        .raw_json(&state_key, &serde_json::to_vec(&state)?)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn archive(post_info: Post) -> Result<(), Errors> {
     let mut headers = HeaderMap::new();
 
     headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0"));
@@ -156,7 +755,6 @@ pub async fn archive(post_info: Post) -> Result<(), Errors> {
         .redirect(Policy::limited(100))
         .default_headers(headers)
         .build()?;
-    let url = format!("https://web.archive.org/save/{}", &post_info.info.url);
 
     // Here we will login to Bluesky, get a JWT token, then get the post
     let auth_response = client
@@ -174,10 +772,28 @@ pub async fn archive(post_info: Post) -> Result<(), Errors> {
         .await?
         .to_vec();
     let creds = serde_json::from_slice::<BskyCreds>(&auth_response)?;
-    let response = &client
+    let store = store_from_env()?;
+
+    // Exit code 100 means no handle/post could be extracted from the URL.
+    let (handle, rkey) = if post_info.info.archive_account {
+        let handle_regex = Regex::new(r"profile/([a-zA-Z0-9._-]+)")?;
+        let handle = match handle_regex.captures(&post_info.info.url) {
+            Some(captures) => captures[1].to_string(),
+            None => exit(100),
+        };
+        (handle, None)
+    } else {
+        let captures = post_info.post_id_regex.captures(&post_info.info.url);
+        let captures = match &captures {
+            Some(captures) => captures,
+            None => exit(100),
+        };
+        (captures[1].to_string(), Some(captures[2].to_string()))
+    };
+
+    let response = client
         .get(format!(
-            "https://bsky.social/xrpc/com.atproto.identity.resolveHandle?handle={}",
-            &post_info_pieces[1],
+            "https://bsky.social/xrpc/com.atproto.identity.resolveHandle?handle={handle}",
         ))
         .send()
         .await?
@@ -185,89 +801,189 @@ pub async fn archive(post_info: Post) -> Result<(), Errors> {
         .await?
         .to_vec();
     let did = serde_json::from_slice::<Did>(&response)?;
-    let response = client
-        .get(format!(
-            "https://bsky.social/xrpc/app.bsky.feed.getPostThread?uri=at://{}/app.bsky.feed.post/{}",
-            did.did, &post_info_pieces[2]
-        ))
-        .bearer_auth(&creds.access_jwt)
-        .send()
-        .await?
-        .bytes()
-        .await?.to_vec();
-    let post_data = serde_json::from_slice::<ThreadData>(&response)?;
 
-    if let Some(post) = post_data.thread.post {
-        if let Some(record) = post.record {
-            println!("Saving post locally...");
+    match &rkey {
+        Some(rkey) => {
+            archive_post_thread(
+                &client,
+                &did,
+                &creds,
+                store.as_ref(),
+                rkey,
+                post_info.depth,
+                post_info.parent_height,
+            )
+            .await?
+        }
+        None => {
+            archive_account_feed(
+                &client,
+                &did,
+                &creds,
+                store.as_ref(),
+                &handle,
+                post_info.depth,
+                post_info.parent_height,
+            )
+            .await?
+        }
+    }
 
-            // Write the post content to a file to preserve its contents locally
-            if !&post_info.posts_dir_exists {
-                fs::create_dir("./posts")?;
-            }
-            fs::create_dir(format!("./posts/{}", &post_info_pieces[2]))?;
+    println!("Post archived successfully.");
 
-            let filename = &format!("./posts/{}/raw.json", &post_info_pieces[2]);
-            let mut file = File::create_new(filename).await?;
+    Ok(())
+}
 
-            file.write_all(&response).await?;
-            println!("Raw post data archived...Saving associated media...");
-            let mut line_counter = 0;
+#[cfg(test)]
+mod media_sniffing_tests {
+    use super::*;
 
-            if let Some(media) = record.embed {
-                for image in media.images {
-                    let referer = &image.image.referer;
-                    let url = format!(
-                        "https://bsky.social/xrpc/com.atproto.sync.getBlob?did={}&cid={}",
-                        &did.did, &referer.cid
-                    );
-                    let response = client.get(&url).send().await?.bytes().await?.to_vec();
-                    let mut image_file = File::create(format!(
-                        "./posts/{}/{}.png",
-                        &post_info_pieces[2], &referer.cid
-                    ))
-                    .await?;
-                    image_file.write(&response).await?;
-                    println!("Saved {}", &referer.cid)
-                }
-                if let Some(video) = media.video {
-                    println!("Saving video from post");
-
-                    // Exit code 101 is for no media type being provided in the response
-                    let media_type = video.mime_type.as_str();
-                    println!("MEDIA TYPE => {media_type}");
-                    let media_type: MediaType = MediaType::from(media_type);
-                    let media_type: &'static str = media_type.into();
-                    let referer = video.referer;
-                    let url_path = format!(
-                        "/xrpc/com.atproto.sync.getBlob?did={}&cid={}",
-                        &did.did, &referer.cid
-                    );
+    #[test]
+    fn sniffs_mp4_from_ftyp_box() {
+        let header = b"\x00\x00\x00\x18ftypmp42\x00\x00\x00\x00";
+        assert_eq!(sniff_video_extension(header), Some("mp4"));
+    }
 
-                    // Get the response headers for the redirect location to get the blob data
-                    let reqwest_response = client
-                        .get(format!("https://bsky.social{url_path}"))
-                        .bearer_auth(&creds.access_jwt)
-                        .send()
-                        .await?;
-                    let reqwest_response = reqwest_response
-                        .bytes()
-                        .await?;
-                    let reqwest_response = reqwest_response
-                        .as_bytes();
-                    let mut video_file = File::create(format!(
-                            "./posts/{}/{}.{}",
-                        &post_info_pieces[2], &referer.cid, media_type
-	                )).await?;
-
-                    video_file.write_all(reqwest_response).await?;
-                }
-            }
-        }
+    #[test]
+    fn sniffs_mov_from_qt_major_brand() {
+        let header = b"\x00\x00\x00\x14ftypqt  \x00\x00\x02\x00";
+        assert_eq!(sniff_video_extension(header), Some("mov"));
     }
-    println!("Archiving externally...");
-    // client.get(url).send().await?;
-    println!("Post archived successfully.");
 
-    Ok(())
+    #[test]
+    fn sniffs_webm_from_ebml_signature() {
+        let header = [0x1A, 0x45, 0xDF, 0xA3, 0, 0, 0, 0];
+        assert_eq!(sniff_video_extension(&header), Some("webm"));
+    }
+
+    #[test]
+    fn sniffs_mpeg_from_pack_or_system_header() {
+        assert_eq!(
+            sniff_video_extension(&[0x00, 0x00, 0x01, 0xBA, 0, 0, 0, 0]),
+            Some("mpeg")
+        );
+        assert_eq!(
+            sniff_video_extension(&[0x00, 0x00, 0x01, 0xB3, 0, 0, 0, 0]),
+            Some("mpeg")
+        );
+    }
+
+    #[test]
+    fn returns_none_for_unrecognized_header() {
+        assert_eq!(sniff_video_extension(&[0u8; 8]), None);
+    }
+
+    #[test]
+    fn sniffs_png_from_magic_bytes() {
+        let header = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        assert_eq!(sniff_image_extension(&header), Some("png"));
+    }
+
+    #[test]
+    fn sniffs_jpeg_from_magic_bytes() {
+        let header = [0xFF, 0xD8, 0xFF, 0xE0];
+        assert_eq!(sniff_image_extension(&header), Some("jpg"));
+    }
+
+    #[test]
+    fn returns_none_for_unrecognized_image_header() {
+        assert_eq!(sniff_image_extension(&[0u8; 8]), None);
+    }
+
+    #[test]
+    fn jpg_maps_to_the_registered_image_slash_jpeg_mime_type() {
+        assert_eq!(image_mime_type("jpg"), "image/jpeg");
+    }
+
+    #[test]
+    fn ico_maps_to_the_registered_microsoft_icon_mime_type() {
+        assert_eq!(image_mime_type("ico"), "image/vnd.microsoft.icon");
+    }
+
+    #[test]
+    fn other_image_extensions_map_to_image_slash_ext() {
+        assert_eq!(image_mime_type("png"), "image/png");
+        assert_eq!(image_mime_type("webp"), "image/webp");
+    }
+
+    #[test]
+    fn mov_gets_the_standard_quicktime_mime_type() {
+        assert_eq!(video_mime_type("mov"), "video/quicktime");
+    }
+
+    #[test]
+    fn other_extensions_map_to_video_slash_ext() {
+        assert_eq!(video_mime_type("mp4"), "video/mp4");
+        assert_eq!(video_mime_type("webm"), "video/webm");
+    }
+}
+
+#[cfg(test)]
+mod blob_store_tests {
+    use super::*;
+
+    #[test]
+    fn shards_by_the_first_two_byte_pairs_of_the_digest() {
+        let digest = "abcd1234ef";
+        assert_eq!(sharded_blob_key(digest, "png"), "blobs/ab/cd/abcd1234ef.png");
+    }
+
+    #[test]
+    fn same_digest_and_extension_always_produce_the_same_key() {
+        let a = sharded_blob_key("deadbeef", "mp4");
+        let b = sharded_blob_key("deadbeef", "mp4");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn different_digests_produce_different_keys() {
+        assert_ne!(sharded_blob_key("aaaa0000", "png"), sharded_blob_key("bbbb0000", "png"));
+    }
+}
+
+#[cfg(test)]
+mod wayback_tests {
+    use super::*;
+
+    #[test]
+    fn prefers_an_absolute_header_location() {
+        let snapshot = resolve_wayback_snapshot_url(
+            Some("https://web.archive.org/web/20260729120000/https://example.com/post"),
+            "https://web.archive.org/save/https://example.com/post",
+        );
+        assert_eq!(
+            snapshot,
+            Some("https://web.archive.org/web/20260729120000/https://example.com/post".to_string())
+        );
+    }
+
+    #[test]
+    fn resolves_a_relative_header_location_against_web_archive_org() {
+        let snapshot = resolve_wayback_snapshot_url(
+            Some("/web/20260729120000/https://example.com/post"),
+            "https://web.archive.org/save/https://example.com/post",
+        );
+        assert_eq!(
+            snapshot,
+            Some("https://web.archive.org/web/20260729120000/https://example.com/post".to_string())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_final_url_when_it_is_a_snapshot() {
+        let snapshot = resolve_wayback_snapshot_url(
+            None,
+            "https://web.archive.org/web/20260729120000/https://example.com/post",
+        );
+        assert_eq!(
+            snapshot,
+            Some("https://web.archive.org/web/20260729120000/https://example.com/post".to_string())
+        );
+    }
+
+    #[test]
+    fn no_header_and_a_non_snapshot_final_url_yields_none() {
+        let snapshot = resolve_wayback_snapshot_url(None, "https://web.archive.org/save/https://example.com/post");
+        assert_eq!(snapshot, None);
+    }
 }
@@ -4,6 +4,11 @@ use regex::Regex;
 #[derive(Debug, Clone)]
 pub struct Post {
     pub info: PostInformation,
-    pub posts_dir_exists: bool,
     pub post_id_regex: Regex,
+    /// How many levels of replies to descend into, passed as
+    /// `getPostThread`'s `depth` query param.
+    pub depth: u32,
+    /// How far up the ancestor chain to climb, passed as
+    /// `getPostThread`'s `parentHeight` query param.
+    pub parent_height: u32,
 }
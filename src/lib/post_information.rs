@@ -1,14 +1,23 @@
 use crate::lib::{Errors, archive, post::Post};
 use eframe::App;
-use egui::{CentralPanel, Color32, Label, Margin, TextEdit, Ui};
+use egui::{CentralPanel, Color32, DragValue, Label, Margin, TextEdit, Ui};
 use regex::Regex;
-use std::fs;
+
+/// Defaults mirror `app.bsky.feed.getPostThread`'s own defaults, so leaving
+/// the fields untouched behaves like the API would with no params set.
+const DEFAULT_DEPTH: u32 = 6;
+const DEFAULT_PARENT_HEIGHT: u32 = 80;
 
 #[derive(Debug, Clone)]
 pub struct PostInformation {
     pub username: String,
     pub password: String,
     pub url: String,
+    pub depth: u32,
+    pub parent_height: u32,
+    /// When set, `url` is treated as a profile URL and every post from that
+    /// account is archived instead of just the one post it points to.
+    pub archive_account: bool,
 }
 
 impl PostInformation {
@@ -17,6 +26,9 @@ impl PostInformation {
             username,
             password,
             url: "".to_string(),
+            depth: DEFAULT_DEPTH,
+            parent_height: DEFAULT_PARENT_HEIGHT,
+            archive_account: false,
         }
     }
 }
@@ -27,6 +39,9 @@ impl Default for PostInformation {
             username: String::new(),
             password: String::new(),
             url: String::new(),
+            depth: DEFAULT_DEPTH,
+            parent_height: DEFAULT_PARENT_HEIGHT,
+            archive_account: false,
         }
     }
 }
@@ -56,15 +71,24 @@ impl PostInformation {
                 .labelled_by(label.id)
             })
         });
+        ui.horizontal(|ui| {
+            ui.label("Reply depth");
+            ui.add(DragValue::new(&mut self.depth).range(0..=1000));
+            ui.label("Parent height");
+            ui.add(DragValue::new(&mut self.parent_height).range(0..=1000));
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.archive_account, "Archive whole account");
+        });
         ui.add_space(10.0);
         if ui.button("Archive").clicked() {
             let info = self.clone();
-            let posts_dir_exists = fs::exists("./posts")?;
             let post_id_regex = Regex::new(r"profile/([a-zA-Z0-9._-]+)/post/([A-Za-z0-9._:~-]+)")?;
             let post = Post {
                 info,
-                posts_dir_exists,
                 post_id_regex,
+                depth: self.depth,
+                parent_height: self.parent_height,
             };
 
             tokio::spawn(archive(post));
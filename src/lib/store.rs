@@ -0,0 +1,348 @@
+use crate::lib::Errors;
+use async_trait::async_trait;
+use aws_sdk_s3::{config::Credentials, primitives::ByteStream, Client as S3Client};
+use std::path::Path;
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Abstracts away *where* archived media and raw post JSON end up, so the
+/// fetch/parse logic in [`crate::lib::archive`] never has to know whether it
+/// is talking to the local filesystem or a remote object store.
+#[async_trait]
+pub trait MediaStore: Send + Sync {
+    /// Writes `bytes` under `key` and returns a human-readable location
+    /// (path or URI) that can be recorded in the post's metadata.
+    async fn put(&self, key: &str, bytes: &[u8], mime: &str) -> Result<String, Errors>;
+
+    /// Reads back whatever was previously written under `key`, or `None` if
+    /// nothing is stored there yet.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Errors>;
+
+    /// Reports whether `key` is already populated, so callers can skip a
+    /// redundant download/write for content that's already archived.
+    async fn exists(&self, key: &str) -> Result<bool, Errors> {
+        Ok(self.get(key).await?.is_some())
+    }
+
+    /// Convenience wrapper for the `raw.json` / `metadata.json` writes, which
+    /// are always `application/json` regardless of backend.
+    async fn raw_json(&self, key: &str, bytes: &[u8]) -> Result<String, Errors> {
+        self.put(key, bytes, "application/json").await
+    }
+
+    /// Moves an already-downloaded file at `local_path` into the store under
+    /// `key`, without needing to hold its contents in memory. Used for large
+    /// media that was streamed to disk rather than buffered.
+    ///
+    /// The default implementation just reads the file back in; backends that
+    /// can stream from disk (e.g. S3 via `ByteStream::from_path`) override it.
+    async fn put_file(&self, key: &str, local_path: &Path, mime: &str) -> Result<String, Errors> {
+        let bytes = fs::read(local_path).await?;
+        let location = self.put(key, &bytes, mime).await?;
+        fs::remove_file(local_path).await?;
+
+        Ok(location)
+    }
+}
+
+/// Shards a hex digest into a `blobs/ab/cd/<digest>.<ext>` key so a single
+/// directory (or S3 "folder") never has to hold every blob ever archived,
+/// while still giving the file a correct, playable extension.
+pub fn sharded_blob_key(digest: &str, ext: &str) -> String {
+    let prefix_a = &digest[0..2.min(digest.len())];
+    let prefix_b = &digest[2..4.min(digest.len())];
+    format!("blobs/{prefix_a}/{prefix_b}/{digest}.{ext}")
+}
+
+/// Preserves today's behaviour: media lives under `<root>/<key>` on the
+/// local filesystem, creating parent directories as needed.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    root: String,
+}
+
+impl FileStore {
+    pub fn new(root: impl Into<String>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl FileStore {
+    fn path_for(&self, key: &str) -> String {
+        format!("{}/{key}", self.root.trim_end_matches('/'))
+    }
+}
+
+#[async_trait]
+impl MediaStore for FileStore {
+    async fn put(&self, key: &str, bytes: &[u8], _mime: &str) -> Result<String, Errors> {
+        let path = self.path_for(key);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::File::create(&path).await?;
+        file.write_all(bytes).await?;
+
+        Ok(path)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Errors> {
+        match fs::read(self.path_for(key)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Errors> {
+        Ok(fs::try_exists(self.path_for(key)).await?)
+    }
+
+    async fn put_file(&self, key: &str, local_path: &Path, _mime: &str) -> Result<String, Errors> {
+        let path = self.path_for(key);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::copy(local_path, &path).await?;
+        fs::remove_file(local_path).await?;
+
+        Ok(path)
+    }
+}
+
+/// Uploads archived media to an S3-compatible bucket instead of keeping it
+/// on the machine running the archiver.
+#[derive(Clone)]
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Store {
+    /// Builds a client from the `.env`-loaded S3 credentials/endpoint.
+    pub fn from_env(bucket: String, prefix: String) -> Result<Self, Errors> {
+        let endpoint = std::env::var("ARCHIVE_S3_ENDPOINT").ok();
+        let region = std::env::var("ARCHIVE_S3_REGION").unwrap_or_else(|_| "us-east-1".into());
+        let access_key = std::env::var("ARCHIVE_S3_ACCESS_KEY")?;
+        let secret_key = std::env::var("ARCHIVE_S3_SECRET_KEY")?;
+
+        let credentials = Credentials::new(access_key, secret_key, None, None, "bsky-archiver");
+        let mut config = aws_sdk_s3::config::Builder::new()
+            .region(aws_sdk_s3::config::Region::new(region))
+            .credentials_provider(credentials)
+            .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest());
+        if let Some(endpoint) = endpoint {
+            config = config.endpoint_url(endpoint).force_path_style(true);
+        }
+
+        Ok(Self {
+            client: S3Client::from_conf(config.build()),
+            bucket,
+            prefix,
+        })
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        match self.prefix.trim_matches('/') {
+            "" => key.to_string(),
+            prefix => format!("{prefix}/{key}"),
+        }
+    }
+}
+
+#[async_trait]
+impl MediaStore for S3Store {
+    async fn put(&self, key: &str, bytes: &[u8], mime: &str) -> Result<String, Errors> {
+        let object_key = self.object_key(key);
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .body(ByteStream::from(bytes.to_vec()))
+            .content_type(mime)
+            .send()
+            .await
+            .map_err(|error| Errors::Store(error.to_string()))?;
+
+        Ok(format!("s3://{}/{object_key}", self.bucket))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Errors> {
+        let object_key = self.object_key(key);
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(error) if error.as_service_error().is_some_and(|e| e.is_no_such_key()) => {
+                return Ok(None)
+            }
+            Err(error) => return Err(Errors::Store(error.to_string())),
+        };
+
+        let bytes = response
+            .body
+            .collect()
+            .await
+            .map_err(|error| Errors::Store(error.to_string()))?
+            .to_vec();
+
+        Ok(Some(bytes))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Errors> {
+        let object_key = self.object_key(key);
+        let response = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .send()
+            .await;
+
+        match response {
+            Ok(_) => Ok(true),
+            Err(error) if error.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(error) => Err(Errors::Store(error.to_string())),
+        }
+    }
+
+    async fn put_file(&self, key: &str, local_path: &Path, mime: &str) -> Result<String, Errors> {
+        let object_key = self.object_key(key);
+        let body = ByteStream::from_path(local_path)
+            .await
+            .map_err(|error| Errors::Store(error.to_string()))?;
+
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&object_key)
+            .body(body)
+            .content_type(mime)
+            .send()
+            .await
+            .map_err(|error| Errors::Store(error.to_string()))?;
+        fs::remove_file(local_path).await?;
+
+        Ok(format!("s3://{}/{object_key}", self.bucket))
+    }
+}
+
+/// Which backend an `ARCHIVE_STORE_URI` value selects, and the location
+/// parsed out of it. Split out from [`store_from_env`] so the scheme/path
+/// parsing can be unit-tested without needing real S3 credentials in the
+/// environment.
+#[derive(Debug, PartialEq)]
+enum StoreUri {
+    File(String),
+    S3 { bucket: String, prefix: String },
+}
+
+/// Parses the scheme and location out of an `ARCHIVE_STORE_URI` value.
+///
+/// Supported schemes:
+/// - `file://<root>` => a [`FileStore`] rooted at `<root>`.
+/// - `s3://<bucket>/<prefix>` => an [`S3Store`] targeting `<bucket>`, with
+///   everything after the first `/` (or nothing) used as the key prefix.
+fn parse_store_uri(uri: &str) -> Result<StoreUri, Errors> {
+    if let Some(root) = uri.strip_prefix("file://") {
+        return Ok(StoreUri::File(root.to_string()));
+    }
+
+    if let Some(rest) = uri.strip_prefix("s3://") {
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        return Ok(StoreUri::S3 {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        });
+    }
+
+    Err(Errors::Store(format!("unrecognized ARCHIVE_STORE_URI scheme: {uri}")))
+}
+
+/// Picks a [`MediaStore`] from `ARCHIVE_STORE_URI`, defaulting to
+/// `file://./posts` so archiving keeps working with no configuration.
+pub fn store_from_env() -> Result<Box<dyn MediaStore>, Errors> {
+    let uri = std::env::var("ARCHIVE_STORE_URI").unwrap_or_else(|_| "file://./posts".to_string());
+
+    match parse_store_uri(&uri)? {
+        StoreUri::File(root) => Ok(Box::new(FileStore::new(root))),
+        StoreUri::S3 { bucket, prefix } => Ok(Box::new(S3Store::from_env(bucket, prefix)?)),
+    }
+}
+
+#[cfg(test)]
+mod store_uri_tests {
+    use super::*;
+
+    #[test]
+    fn file_scheme_keeps_the_root_verbatim() {
+        assert_eq!(parse_store_uri("file://./posts").unwrap(), StoreUri::File("./posts".to_string()));
+    }
+
+    #[test]
+    fn s3_scheme_splits_bucket_and_prefix() {
+        assert_eq!(
+            parse_store_uri("s3://my-bucket/some/prefix").unwrap(),
+            StoreUri::S3 {
+                bucket: "my-bucket".to_string(),
+                prefix: "some/prefix".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn s3_scheme_without_a_prefix_defaults_to_empty() {
+        assert_eq!(
+            parse_store_uri("s3://my-bucket").unwrap(),
+            StoreUri::S3 {
+                bucket: "my-bucket".to_string(),
+                prefix: "".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn unrecognized_scheme_is_an_error() {
+        assert!(matches!(parse_store_uri("ftp://nope"), Err(Errors::Store(_))));
+    }
+}
+
+#[cfg(test)]
+mod object_key_tests {
+    use super::*;
+
+    fn store_with_prefix(prefix: &str) -> S3Store {
+        let config = aws_sdk_s3::config::Builder::new()
+            .region(aws_sdk_s3::config::Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+            .build();
+
+        S3Store {
+            client: S3Client::from_conf(config),
+            bucket: "test-bucket".to_string(),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    #[test]
+    fn empty_prefix_leaves_the_key_unchanged() {
+        assert_eq!(store_with_prefix("").object_key("blobs/ab/cd/digest.png"), "blobs/ab/cd/digest.png");
+    }
+
+    #[test]
+    fn prefix_is_joined_with_a_slash() {
+        assert_eq!(store_with_prefix("archive").object_key("blobs/ab/cd/digest.png"), "archive/blobs/ab/cd/digest.png");
+    }
+
+    #[test]
+    fn surrounding_slashes_on_the_prefix_are_trimmed() {
+        assert_eq!(store_with_prefix("/archive/").object_key("raw.json"), "archive/raw.json");
+    }
+}